@@ -51,14 +51,121 @@
 //!     assert_eq!(WellUsedNewCar.has_bluetooth(), true);
 //! }
 //! ```
+//!
+//! # Generic traits
+//!
+//! Generic traits, type parameters, and `where` clauses are supported on both macros:
+//!
+//! ```
+//! #[default_trait_impl]
+//! impl<T: Clone> Store<T> for NewStore<T> where T: std::fmt::Debug {
+//!     fn get(&self) -> Option<T> { None }
+//! }
+//!
+//! #[trait_impl]
+//! impl<T: Clone> NewStore<T> for MyStore<T> {
+//!     fn put(&mut self, _item: T) {}
+//! }
+//! ```
+//!
+//! If the consuming `impl` omits its own generics or `where` clause, the ones captured from the
+//! default implementation are spliced in automatically.
+//!
+//! # Runtime registration
+//!
+//! Passing `register` to *both* `default_trait_impl` and `trait_impl` opts a type into a runtime
+//! registry of every implementor of a pseudotrait, which is handy for opcode tables, plugin sets,
+//! or card-game-style dispatch where many types share one default impl:
+//!
+//! ```
+//! #[default_trait_impl(register)]
+//! impl Car for NewCar {
+//!     fn get_mileage(&self) -> Option<usize> { Some(6000) }
+//!     fn has_bluetooth(&self) -> bool { true }
+//! }
+//!
+//! #[trait_impl(register)]
+//! impl NewCar for NewOldFashionedCar {
+//!     fn has_bluetooth(&self) -> bool { false }
+//! }
+//! ```
+//!
+//! This generates `newcar_instances()` (an iterator over one boxed instance of every type
+//! registered against the `NewCar` pseudotrait) and `newcar_instance_by_name("NewOldFashionedCar")`.
+//! The functions are named after the pseudotrait, not the real trait, so that two pseudotraits
+//! sharing one real trait (see `extends` below) don't collide. The real trait must be object-safe
+//! and non-generic, and the registered type must implement `Default`, since the registry
+//! constructs each instance via `Default::default()`. This feature relies on the `inventory`
+//! crate, which must also be a direct dependency of any crate using `register`, and is entirely
+//! opt-in: a `default_trait_impl` without `register` emits no registry scaffolding at all.
+//!
+//! # Extending another default implementation
+//!
+//! A `default_trait_impl` can itself build on a previously defined one, so specialized variants
+//! don't need to copy-paste the base mock:
+//!
+//! ```
+//! #[default_trait_impl(extends = NewCar)]
+//! impl Car for SportsCar {
+//!     fn has_bluetooth(&self) -> bool { true }
+//! }
+//! ```
+//!
+//! `SportsCar` inherits every item from `NewCar` that it does not itself define, and that
+//! inheritance chains transitively through further `extends`.
+//!
+//! # `unsafe` and `default` impls
+//!
+//! An `unsafe impl` or specialization's `default impl` keeps its qualifier even when a
+//! `trait_impl` consumer doesn't repeat it:
+//!
+//! ```
+//! #[default_trait_impl]
+//! unsafe impl Marker for NewMarker {}
+//!
+//! #[trait_impl]
+//! impl NewMarker for MyMarker {}
+//! ```
+//!
+//! `MyMarker`'s generated impl is `unsafe impl Marker for MyMarker` unless `MyMarker`'s own
+//! `impl` already specifies `unsafe` or `default` itself, in which case that is left alone.
+//!
+//! # Calling the inherited default from an override
+//!
+//! Passing `shadow` to `trait_impl` additionally generates a private `__default_<method>` copy of
+//! every default method, so an override can delegate to it instead of rewriting its logic:
+//!
+//! ```
+//! #[trait_impl(shadow)]
+//! impl NewCar for OdometerRolledOver {
+//!     fn get_mileage(&self) -> Option<usize> {
+//!         self.__default_get_mileage().map(|m| m + 1)
+//!     }
+//! }
+//! ```
+//!
+//! Without `shadow`, no `__default_*` methods are generated, to avoid polluting the type's
+//! namespace when nobody needs them.
+//!
+//! # Lifetime elision
+//!
+//! A default method's elided reference arguments — including `&self`, `&dyn Trait`, and `impl
+//! Trait` — are each given a fresh named lifetime before being spliced into a consumer impl, and
+//! an elided output reference (e.g. `fn name(&self) -> &str`) reuses that same lifetime, so
+//! elision resolves the same way regardless of the consumer's own generics. This is internal
+//! bookkeeping; `'__mdti_*` lifetimes never need to be written by hand.
 
 extern crate proc_macro;
 use proc_macro::TokenStream;
 use proc_macro2::Span;
-use quote::quote;
+use quote::{format_ident, quote};
 use std::collections::{HashMap, HashSet};
 use std::sync::Mutex;
-use syn::{parse_macro_input, parse_str, Ident, ImplItem, ImplItemMethod, ItemImpl, Type};
+use syn::{
+    parse_macro_input, parse_str, AttributeArgs, FnArg, GenericParam, Generics, Ident, ImplItem,
+    ImplItemMethod, ItemImpl, Lifetime, LifetimeDef, Meta, NestedMeta, Pat, Path, PathArguments,
+    ReturnType, Token, Type, TypeParamBound,
+};
 
 #[macro_use]
 extern crate lazy_static;
@@ -69,31 +176,107 @@ lazy_static! {
 }
 
 struct DefaultTraitImpl {
-    pub trait_name: String,
+    pub trait_path: String,
+    pub trait_ident: String,
+    pub generics: String,
+    pub unsafety: bool,
+    pub defaultness: bool,
+    pub registry: bool,
     pub items: Vec<String>,
 }
 
+/// Arguments accepted by `#[default_trait_impl(...)]`, e.g. `extends = NewCar, register`.
+struct DefaultTraitImplArgs {
+    extends: Option<Ident>,
+    register: bool,
+}
+
+impl syn::parse::Parse for DefaultTraitImplArgs {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut extends = None;
+        let mut register = false;
+
+        while !input.is_empty() {
+            let keyword: Ident = input.parse()?;
+            if keyword == "extends" {
+                input.parse::<syn::Token![=]>()?;
+                extends = Some(input.parse()?);
+            } else if keyword == "register" {
+                register = true;
+            } else {
+                return Err(syn::Error::new(
+                    keyword.span(),
+                    "expected `extends = <PseudoTrait>` or `register`",
+                ));
+            }
+
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<syn::Token![,]>()?;
+        }
+
+        Ok(DefaultTraitImplArgs { extends, register })
+    }
+}
+
 #[proc_macro_attribute]
-pub fn default_trait_impl(_: TokenStream, input: TokenStream) -> TokenStream {
+pub fn default_trait_impl(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as DefaultTraitImplArgs);
     let input = parse_macro_input!(input as ItemImpl);
 
-    let pseudotrait = match *input.self_ty {
-        Type::Path(type_path) => match type_path.path.get_ident() {
-            Some(ident) => ident.to_string(),
+    let pseudotrait = match &*input.self_ty {
+        Type::Path(type_path) => match type_path.path.segments.last() {
+            Some(segment) => segment.ident.to_string(),
             None => return syntax_invalid_error(),
         },
         _ => return syntax_invalid_error(),
     };
 
-    let trait_name = match input.trait_ {
-        Some(trait_tuple) => match trait_tuple.1.get_ident() {
-            Some(ident) => ident.to_string(),
-            None => return syntax_invalid_error(),
-        },
+    let trait_path = match &input.trait_ {
+        Some(trait_tuple) => &trait_tuple.1,
         _ => return syntax_invalid_error(),
     };
+    let last_trait_segment = match trait_path.segments.last() {
+        Some(segment) => segment,
+        None => return syntax_invalid_error(),
+    };
+    let trait_ident = last_trait_segment.ident.to_string();
+    let trait_has_generics = !matches!(last_trait_segment.arguments, PathArguments::None);
+
+    if args.register && trait_has_generics {
+        return compile_error(&format!(
+            "`register` is not supported for generic traits like `{}`, since the registry can't \
+             know what type arguments to box",
+            trait_ident
+        ));
+    }
+
+    let trait_path = quote! { #trait_path }.to_string();
+
+    let generics = &input.generics;
+    let generics = quote! { #generics }.to_string();
 
-    let items: Vec<String> = input
+    let unsafety = input.unsafety.is_some();
+    let defaultness = input.defaultness.is_some();
+
+    let mut idents = HashSet::new();
+    for item in &input.items {
+        match item {
+            ImplItem::Method(method) => {
+                idents.insert(method.sig.ident.to_string());
+            }
+            ImplItem::Const(constant) => {
+                idents.insert(constant.ident.to_string());
+            }
+            ImplItem::Type(ty) => {
+                idents.insert(ty.ident.to_string());
+            }
+            _ => (),
+        };
+    }
+
+    let mut items: Vec<String> = input
         .items
         .iter()
         .map(|item| {
@@ -104,12 +287,107 @@ pub fn default_trait_impl(_: TokenStream, input: TokenStream) -> TokenStream {
         })
         .collect();
 
-    DEFAULT_TRAIT_IMPLS
-        .lock()
-        .unwrap()
-        .insert(pseudotrait, DefaultTraitImpl { trait_name, items });
+    if let Some(parent) = &args.extends {
+        match resolve_extends(&pseudotrait, &parent.to_string()) {
+            Ok(parent_items) => {
+                for parent_item in parent_items {
+                    let parsed: ImplItem = parse_str(&parent_item).unwrap();
+                    let ident = match &parsed {
+                        ImplItem::Method(method) => method.sig.ident.to_string(),
+                        ImplItem::Const(constant) => constant.ident.to_string(),
+                        ImplItem::Type(ty) => ty.ident.to_string(),
+                        _ => continue,
+                    };
+                    if idents.insert(ident) {
+                        items.push(parent_item);
+                    }
+                }
+            }
+            Err(err) => return err,
+        }
+    }
 
-    TokenStream::new()
+    let registry_support = if args.register {
+        registry_support(&pseudotrait, &trait_ident)
+    } else {
+        TokenStream::new()
+    };
+
+    DEFAULT_TRAIT_IMPLS.lock().unwrap().insert(
+        pseudotrait,
+        DefaultTraitImpl {
+            trait_path,
+            trait_ident,
+            generics,
+            unsafety,
+            defaultness,
+            registry: args.register,
+            items,
+        },
+    );
+
+    registry_support
+}
+
+/// Looks up the flattened item list of a pseudotrait named by an `extends = ...` argument.
+/// Since every `DefaultTraitImpl` already stores its own ancestors' items (flattened when *it*
+/// was defined), a single lookup here is enough to resolve the whole chain transitively.
+fn resolve_extends(pseudotrait: &str, parent: &str) -> Result<Vec<String>, TokenStream> {
+    if parent == pseudotrait {
+        return Err(compile_error(&format!(
+            "`extends` chain for `{}` contains a cycle",
+            pseudotrait
+        )));
+    }
+
+    match DEFAULT_TRAIT_IMPLS.lock().unwrap().get(parent) {
+        Some(parent_impl) => Ok(parent_impl.items.clone()),
+        None => Err(compile_error(&format!(
+            "`extends = {}` expects `{}` to already have a `default_trait_impl`",
+            parent, parent
+        ))),
+    }
+}
+
+fn compile_error(message: &str) -> TokenStream {
+    quote! { compile_error!(#message); }.into()
+}
+
+/// Emits the `inventory` scaffolding and the `<pseudotrait>_instances`/
+/// `<pseudotrait>_instance_by_name` factory functions for a pseudotrait, so that
+/// `trait_impl(register)` has somewhere to submit to. Named after the pseudotrait (not the real
+/// trait) since several pseudotraits can share one real trait via `extends`.
+fn registry_support(pseudotrait: &str, trait_ident: &str) -> TokenStream {
+    let registry_struct = format_ident!("__MultiDefaultTraitImplRegistry{}", pseudotrait);
+    let trait_ident = Ident::new(trait_ident, Span::call_site());
+    let factory_all = format_ident!("{}_instances", pseudotrait.to_lowercase());
+    let factory_by_name = format_ident!("{}_instance_by_name", pseudotrait.to_lowercase());
+
+    let res = quote! {
+        #[doc(hidden)]
+        pub struct #registry_struct {
+            pub name: &'static str,
+            pub ctor: fn() -> ::std::boxed::Box<dyn #trait_ident>,
+        }
+
+        inventory::collect!(#registry_struct);
+
+        /// Constructs one boxed instance of every type registered against this default
+        /// implementation via `#[trait_impl(register)]`.
+        pub fn #factory_all() -> impl Iterator<Item = ::std::boxed::Box<dyn #trait_ident>> {
+            inventory::iter::<#registry_struct>.into_iter().map(|registered| (registered.ctor)())
+        }
+
+        /// Looks up a type registered via `#[trait_impl(register)]` by its type name and
+        /// constructs it, or returns `None` if no such type was registered.
+        pub fn #factory_by_name(name: &str) -> ::std::option::Option<::std::boxed::Box<dyn #trait_ident>> {
+            inventory::iter::<#registry_struct>
+                .into_iter()
+                .find(|registered| registered.name == name)
+                .map(|registered| (registered.ctor)())
+        }
+    };
+    res.into()
 }
 
 fn syntax_invalid_error() -> TokenStream {
@@ -119,12 +397,22 @@ fn syntax_invalid_error() -> TokenStream {
 }
 
 #[proc_macro_attribute]
-pub fn trait_impl(_: TokenStream, input: TokenStream) -> TokenStream {
+pub fn trait_impl(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let register = args.iter().any(|arg| match arg {
+        NestedMeta::Meta(Meta::Path(path)) => path.is_ident("register"),
+        _ => false,
+    });
+    let shadow_defaults = args.iter().any(|arg| match arg {
+        NestedMeta::Meta(Meta::Path(path)) => path.is_ident("shadow"),
+        _ => false,
+    });
+
     let mut input = parse_macro_input!(input as ItemImpl);
 
     let trait_name = match &input.trait_ {
-        Some(trait_tuple) => match trait_tuple.1.get_ident() {
-            Some(ident) => ident.to_string(),
+        Some(trait_tuple) => match trait_tuple.1.segments.last() {
+            Some(segment) => segment.ident.to_string(),
             None => return syntax_invalid_error(),
         },
         _ => return syntax_invalid_error(),
@@ -146,14 +434,57 @@ pub fn trait_impl(_: TokenStream, input: TokenStream) -> TokenStream {
         };
     }
 
+    let mut registration = quote! {};
+    let mut shadow_methods: Vec<ImplItemMethod> = Vec::new();
+
     match DEFAULT_TRAIT_IMPLS.lock().unwrap().get(&trait_name) {
         Some(default_impl) => {
+            if register {
+                if !default_impl.registry {
+                    return compile_error(&format!(
+                        "`trait_impl(register)` requires `{}`'s `default_trait_impl` to also be \
+                         declared with `register`",
+                        trait_name
+                    ));
+                }
+                registration =
+                    registration_tokens(&trait_name, &default_impl.trait_ident, &input.self_ty);
+            }
+
             if let Some(trait_tuple) = &mut input.trait_ {
-                trait_tuple.1.segments[0].ident = Ident::new(&default_impl.trait_name, Span::call_site());
+                trait_tuple.1 = parse_str::<Path>(&default_impl.trait_path).unwrap();
+            }
+
+            let default_generics: Generics = parse_str(&default_impl.generics).unwrap();
+            if input.generics.params.is_empty() {
+                input.generics.lt_token = default_generics.lt_token;
+                input.generics.params = default_generics.params.clone();
+                input.generics.gt_token = default_generics.gt_token;
+            }
+            if input.generics.where_clause.is_none() {
+                input.generics.where_clause = default_generics.where_clause.clone();
+            }
+
+            if input.unsafety.is_none() && default_impl.unsafety {
+                input.unsafety = Some(<Token![unsafe]>::default());
+            }
+            if input.defaultness.is_none() && default_impl.defaultness {
+                input.defaultness = Some(<Token![default]>::default());
             }
 
             for default_impl_item in &default_impl.items {
-                let parsed_result: ImplItem = parse_str(default_impl_item).unwrap();
+                let mut parsed_result: ImplItem = parse_str(default_impl_item).unwrap();
+
+                if let ImplItem::Method(method) = &mut parsed_result {
+                    normalize_elided_lifetimes(method);
+                }
+
+                if shadow_defaults {
+                    if let ImplItem::Method(method) = &parsed_result {
+                        shadow_methods.push(shadow_default_method(method));
+                    }
+                }
+
                 match parsed_result{
                     ImplItem::Method(method) if !idents.contains(&method.sig.ident.to_string()) =>{
                         input.items.push(ImplItem::Method(method));
@@ -177,8 +508,168 @@ pub fn trait_impl(_: TokenStream, input: TokenStream) -> TokenStream {
         }.into()
     }
 
+    let shadow_impl = if shadow_methods.is_empty() {
+        quote! {}
+    } else {
+        let self_ty = &input.self_ty;
+        let (impl_generics, _, where_clause) = input.generics.split_for_impl();
+        quote! {
+            impl #impl_generics #self_ty #where_clause {
+                #(#shadow_methods)*
+            }
+        }
+    };
+
     let res = quote! {
         #input
+        #registration
+        #shadow_impl
     };
     res.into()
 }
+
+/// Clones a default method into a private, `__default_`-prefixed copy so that an overriding
+/// method can delegate to it, e.g. `self.__default_get_mileage()`. Generics and argument
+/// lifetimes come along for free since the whole signature is cloned verbatim.
+fn shadow_default_method(method: &ImplItemMethod) -> ImplItemMethod {
+    let mut shadow = method.clone();
+    shadow.sig.ident = format_ident!("__default_{}", method.sig.ident);
+    shadow
+}
+
+/// Gives every elided reference in a default method's receiver and arguments (including
+/// lifetime-less `&dyn Trait`/`impl Trait` bounds) its own fresh named lifetime, registered on the
+/// method's own generics, and makes an elided output reference reuse that lifetime too. This
+/// keeps the merged impl's elision behavior stable regardless of the consumer's surrounding
+/// generics, since the method is spliced into a signature it wasn't originally written against.
+fn normalize_elided_lifetimes(method: &mut ImplItemMethod) {
+    let mut synthesized = Vec::new();
+    // The lifetime an elided output reference should reuse: `&self`'s, named or elided (mirroring
+    // Rust's own elision rule of preferring `&self`), else the first argument we had to name.
+    let mut elision_source: Option<Lifetime> = None;
+
+    if let Some(FnArg::Receiver(receiver)) = method.sig.inputs.first_mut() {
+        if let Some(reference) = &mut receiver.reference {
+            if let Some(existing) = &reference.1 {
+                elision_source = Some(existing.clone());
+            } else {
+                let new_lifetime = Lifetime::new("'__mdti_self", Span::call_site());
+                reference.1 = Some(new_lifetime.clone());
+                synthesized.push(new_lifetime.clone());
+                elision_source = Some(new_lifetime);
+            }
+        }
+    }
+
+    for (index, arg) in method.sig.inputs.iter_mut().enumerate() {
+        let pat_type = match arg {
+            FnArg::Typed(pat_type) => pat_type,
+            FnArg::Receiver(_) => continue,
+        };
+
+        let label = match &*pat_type.pat {
+            Pat::Ident(pat_ident) => pat_ident.ident.to_string(),
+            _ => index.to_string(),
+        };
+        let lifetime_name = format!("'__mdti_{}", label);
+        let lifetime = Lifetime::new(&lifetime_name, Span::call_site());
+
+        if add_elided_lifetime(&mut pat_type.ty, &lifetime) {
+            if elision_source.is_none() {
+                elision_source = Some(lifetime.clone());
+            }
+            synthesized.push(lifetime);
+        }
+    }
+
+    if let ReturnType::Type(_, ty) = &mut method.sig.output {
+        if let Some(source) = &elision_source {
+            add_elided_lifetime(ty, source);
+        }
+    }
+
+    for lifetime in synthesized {
+        method
+            .sig
+            .generics
+            .params
+            .insert(0, GenericParam::Lifetime(LifetimeDef::new(lifetime)));
+    }
+}
+
+/// Inserts `lifetime` into `ty` wherever it is missing an explicit one, returning whether it was
+/// used at all (so the caller only registers lifetimes that were actually needed).
+fn add_elided_lifetime(ty: &mut Type, lifetime: &Lifetime) -> bool {
+    match ty {
+        Type::Reference(type_ref) => {
+            let mut used = false;
+            if type_ref.lifetime.is_none() {
+                type_ref.lifetime = Some(lifetime.clone());
+                used = true;
+            }
+            if add_elided_lifetime(&mut type_ref.elem, lifetime) {
+                used = true;
+                // `&'a dyn Trait + 'a` does not parse, so a bound added behind a reference is
+                // parenthesised: `&'a (dyn Trait + 'a)`.
+                if matches!(&*type_ref.elem, Type::TraitObject(_) | Type::ImplTrait(_)) {
+                    let elem = (*type_ref.elem).clone();
+                    *type_ref.elem = Type::Paren(syn::TypeParen {
+                        paren_token: Default::default(),
+                        elem: Box::new(elem),
+                    });
+                }
+            }
+            used
+        }
+        Type::TraitObject(trait_object) => {
+            add_missing_lifetime_bound(&mut trait_object.bounds, lifetime)
+        }
+        Type::ImplTrait(impl_trait) => add_missing_lifetime_bound(&mut impl_trait.bounds, lifetime),
+        _ => false,
+    }
+}
+
+fn add_missing_lifetime_bound(
+    bounds: &mut syn::punctuated::Punctuated<TypeParamBound, Token![+]>,
+    lifetime: &Lifetime,
+) -> bool {
+    if bounds
+        .iter()
+        .any(|bound| matches!(bound, TypeParamBound::Lifetime(_)))
+    {
+        return false;
+    }
+    bounds.push(TypeParamBound::Lifetime(lifetime.clone()));
+    true
+}
+
+/// Emits the `Default`/object-safety assertions and the `inventory::submit!` call that registers
+/// `self_ty` as an implementor of `pseudotrait`, for consumers using `#[trait_impl(register)]`.
+fn registration_tokens(
+    pseudotrait: &str,
+    trait_ident: &str,
+    self_ty: &Type,
+) -> proc_macro2::TokenStream {
+    let registry_struct = format_ident!("__MultiDefaultTraitImplRegistry{}", pseudotrait);
+    let trait_ident: Path = match parse_str(trait_ident) {
+        Ok(path) => path,
+        Err(_) => return quote! {},
+    };
+    let self_ty_name = quote! { #self_ty }.to_string();
+
+    quote! {
+        const _: fn() = || {
+            fn __mdti_assert_default<T: ::std::default::Default>() {}
+            #[allow(dead_code)]
+            fn __mdti_assert_object_safe(_: ::std::boxed::Box<dyn #trait_ident>) {}
+            __mdti_assert_default::<#self_ty>();
+        };
+
+        inventory::submit! {
+            #registry_struct {
+                name: #self_ty_name,
+                ctor: || ::std::boxed::Box::new(<#self_ty as ::std::default::Default>::default()) as ::std::boxed::Box<dyn #trait_ident>,
+            }
+        }
+    }
+}